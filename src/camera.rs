@@ -0,0 +1,37 @@
+//! The camera: a smoothly-following `Frame` whose `position` is the
+//! world-space top-left corner of the viewport. Entities keep a single true
+//! world position; `draw` subtracts the camera's position to get screen
+//! coordinates, instead of the old approach of physically shifting every
+//! entity to scroll the world around the player.
+
+use nalgebra as na;
+
+use crate::{CAMERA_FOLLOW_FACTOR, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub struct Frame {
+    pub position: na::Vector2<f32>,
+}
+
+impl Frame {
+    pub fn new() -> Frame {
+        Frame { position: na::Vector2::new(0.0, 0.0) }
+    }
+
+    /// Lerps toward `target`, then clamps to `[0, level_size - screen_size]`
+    /// on each axis, centering instead if the level is smaller than the
+    /// screen, so the view never scrolls past the level's edges.
+    pub fn update(&mut self, target: na::Vector2<f32>, level_width: f32, level_height: f32) {
+        self.position += (target - self.position) * CAMERA_FOLLOW_FACTOR;
+
+        self.position.x = clamp_axis(self.position.x, level_width, SCREEN_WIDTH);
+        self.position.y = clamp_axis(self.position.y, level_height, SCREEN_HEIGHT);
+    }
+}
+
+fn clamp_axis(position: f32, level_size: f32, screen_size: f32) -> f32 {
+    if level_size <= screen_size {
+        (level_size - screen_size) / 2.0
+    } else {
+        position.max(0.0).min(level_size - screen_size)
+    }
+}
@@ -0,0 +1,176 @@
+//! A minimal entity-component-system core.
+//!
+//! Entities are plain ids; components are stored in typed maps owned by the
+//! `Manager`, keyed by entity id. Gameplay behavior lives in `System`s and
+//! `RenderSystem`s registered with `Manager::add_system` /
+//! `Manager::add_render_system`, which run once per tick over whichever
+//! entities carry the components they care about. This is what lets new
+//! gameplay objects show up as "entity + components" instead of a new
+//! hand-written object type.
+
+use std::collections::HashMap;
+
+use nalgebra as na;
+
+use ggez::{Context, GameResult};
+
+use crate::scenes::SharedGameState;
+
+pub type EntityId = u32;
+
+// --- components ---------------------------------------------------------
+
+#[derive(Clone, Copy)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Velocity {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Bounds {
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Per-tick movement intent. Only entities driven by a player or AI carry
+/// this.
+#[derive(Clone, Copy)]
+pub struct InputIntent {
+    pub acceleration: nalgebra::Vector2<f32>,
+}
+
+/// Player-specific bookkeeping that doesn't generalize to other entities
+/// (yet): jump state, the idle "pulse" animation, lean rotation, the
+/// temporary speed-up power-up timer, and which moving platform (if any) is
+/// currently carrying the player.
+pub struct PlayerState {
+    pub jumping: bool,
+    pub alpha: f32,
+    pub rotation: f32,
+    pub speed_up_counter: i32,
+    pub supporting_platform: Option<EntityId>,
+}
+
+/// Marks an entity as a flat, solid obstacle: walls on the sides, a floor on
+/// top. `SlopeShape` entities deliberately do not carry this, since their
+/// sides must never push the player sideways.
+pub struct Solid;
+
+/// Sloped floor geometry: a ramp spanning `width_segments` tiles, `height_segments` tiles
+/// tall, rising from low-on-the-left to high-on-the-right when `rising` is
+/// `true`.
+pub struct SlopeShape {
+    pub width_segments: i32,
+    pub height_segments: i32,
+    pub rising: bool,
+}
+
+/// How an entity with a `Position` should be drawn.
+pub enum Renderable {
+    Player,
+    Tiles { image: TileImage, width_segments: i32, height_segments: i32 },
+}
+
+#[derive(Clone, Copy)]
+pub enum TileImage {
+    Platform,
+}
+
+/// Which axis a `Patrol` entity paces back and forth along.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PatrolAxis {
+    Horizontal,
+    Vertical,
+}
+
+/// Marks a `Solid` entity as a moving platform: it paces between `min` and
+/// `max` along `axis`, reversing its own `Velocity` at each end. Carrying the
+/// player that's resting on it is handled separately, by `PlayerState`'s
+/// `supporting_platform`.
+pub struct Patrol {
+    pub axis: PatrolAxis,
+    pub min: f32,
+    pub max: f32,
+}
+
+// --- systems -------------------------------------------------------------
+
+pub trait System {
+    fn update(&self, manager: &mut Manager);
+}
+
+pub trait RenderSystem {
+    /// `camera` is the world-space top-left corner of the view; render
+    /// systems draw at `world_position - camera` to get screen coordinates.
+    fn draw(&self, manager: &Manager, shared: &SharedGameState, camera: na::Vector2<f32>, ctx: &mut Context) -> GameResult<()>;
+}
+
+// --- manager ---------------------------------------------------------------
+
+#[derive(Default)]
+pub struct Manager {
+    next_entity_id: EntityId,
+
+    pub positions: HashMap<EntityId, Position>,
+    pub velocities: HashMap<EntityId, Velocity>,
+    pub bounds: HashMap<EntityId, Bounds>,
+    pub input_intents: HashMap<EntityId, InputIntent>,
+    pub player_states: HashMap<EntityId, PlayerState>,
+    pub solids: HashMap<EntityId, Solid>,
+    pub slopes: HashMap<EntityId, SlopeShape>,
+    pub patrols: HashMap<EntityId, Patrol>,
+    pub renderables: HashMap<EntityId, Renderable>,
+
+    systems: Vec<Box<dyn System>>,
+    render_systems: Vec<Box<dyn RenderSystem>>,
+}
+
+impl Manager {
+    pub fn new() -> Manager {
+        Manager::default()
+    }
+
+    pub fn spawn(&mut self) -> EntityId {
+        let id = self.next_entity_id;
+        self.next_entity_id += 1;
+        id
+    }
+
+    pub fn add_system(&mut self, system: impl System + 'static) {
+        self.systems.push(Box::new(system));
+    }
+
+    pub fn add_render_system(&mut self, system: impl RenderSystem + 'static) {
+        self.render_systems.push(Box::new(system));
+    }
+
+    pub fn run_systems(&mut self) {
+        // This can't borrow `self.systems` for the loop and `&mut self` for
+        // the body at once; take the list out for the duration of the tick
+        // instead.
+        let systems = std::mem::take(&mut self.systems);
+        for system in &systems {
+            system.update(self);
+        }
+        self.systems = systems;
+    }
+
+    pub fn draw(&self, shared: &SharedGameState, camera: na::Vector2<f32>, ctx: &mut Context) -> GameResult<()> {
+        for system in &self.render_systems {
+            system.draw(self, shared, camera, ctx)?;
+        }
+        Ok(())
+    }
+
+    pub fn rect_of(&self, entity: EntityId) -> Option<ggez::graphics::Rect> {
+        let position = self.positions.get(&entity)?;
+        let bounds = self.bounds.get(&entity)?;
+        Some(ggez::graphics::Rect::new(position.x, position.y, bounds.width, bounds.height))
+    }
+}
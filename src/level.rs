@@ -0,0 +1,132 @@
+//! Data-driven level loading: a level is a text file under
+//! `resources/levels/` laid out as a grid of tile characters (`.` empty,
+//! `#` solid ground, `^` slope, `=` a horizontal patrol track, `|` a
+//! vertical elevator track, `@` the player's spawn point), read through
+//! ggez's resource filesystem so it ships alongside the images.
+
+use std::io::Read;
+
+use ggez::{Context, GameError, GameResult};
+
+use crate::TILE_SIZE;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tile {
+    Empty,
+    Solid,
+    Slope,
+    PatrolHorizontal,
+    PatrolVertical,
+}
+
+/// A loaded level: its tile grid plus the derived world-space spawn point and
+/// bounds, so the camera/world can know its limits without re-parsing.
+pub struct Level {
+    pub tiles: Vec<Vec<Tile>>,
+    pub width_tiles: i32,
+    pub height_tiles: i32,
+    pub player_spawn: (f32, f32),
+}
+
+impl Level {
+    pub fn load(ctx: &mut Context, name: &str) -> GameResult<Level> {
+        let path = format!("/levels/{}.txt", name);
+        let mut file = ggez::filesystem::open(ctx, path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Level::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> GameResult<Level> {
+        let mut tiles = Vec::new();
+        let mut player_spawn = None;
+
+        for (row, line) in contents.lines().enumerate() {
+            let mut tile_row = Vec::new();
+            for (col, ch) in line.chars().enumerate() {
+                let tile = match ch {
+                    '.' => Tile::Empty,
+                    '#' => Tile::Solid,
+                    '^' => Tile::Slope,
+                    '=' => Tile::PatrolHorizontal,
+                    '|' => Tile::PatrolVertical,
+                    '@' => {
+                        player_spawn = Some((col as f32 * TILE_SIZE, row as f32 * TILE_SIZE));
+                        Tile::Empty
+                    }
+                    _ => Tile::Empty,
+                };
+                tile_row.push(tile);
+            }
+            tiles.push(tile_row);
+        }
+
+        let player_spawn = player_spawn.ok_or_else(||
+            GameError::ResourceLoadError("level is missing a '@' player spawn tile".to_string()))?;
+
+        let height_tiles = tiles.len() as i32;
+        let width_tiles = tiles.iter().map(|row| row.len()).max().unwrap_or(0) as i32;
+
+        Ok(Level { tiles, width_tiles, height_tiles, player_spawn })
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width_tiles as f32 * TILE_SIZE
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height_tiles as f32 * TILE_SIZE
+    }
+
+    fn tile_at(&self, row: usize, col: usize) -> Tile {
+        self.tiles.get(row).and_then(|r| r.get(col)).cloned().unwrap_or(Tile::Empty)
+    }
+
+    /// Collapses each row's runs of adjacent matching tiles into
+    /// `(start_col, end_col, row)` spans, so the loader can spawn one
+    /// platform/slope entity per run instead of one per tile.
+    pub fn runs_of(&self, wanted: Tile) -> Vec<(usize, usize, usize)> {
+        let mut runs = Vec::new();
+
+        for row in 0..self.tiles.len() {
+            let mut col = 0;
+            while col < self.tiles[row].len() {
+                if self.tile_at(row, col) == wanted {
+                    let start = col;
+                    while col < self.tiles[row].len() && self.tile_at(row, col) == wanted {
+                        col += 1;
+                    }
+                    runs.push((start, col, row));
+                } else {
+                    col += 1;
+                }
+            }
+        }
+
+        runs
+    }
+
+    /// Same as `runs_of`, but scans each column top-to-bottom instead of each
+    /// row left-to-right, for vertically-oriented spans like an elevator's
+    /// track. Returns `(start_row, end_row, col)` spans.
+    pub fn vertical_runs_of(&self, wanted: Tile) -> Vec<(usize, usize, usize)> {
+        let mut runs = Vec::new();
+
+        for col in 0..self.width_tiles as usize {
+            let mut row = 0;
+            while row < self.tiles.len() {
+                if self.tile_at(row, col) == wanted {
+                    let start = row;
+                    while row < self.tiles.len() && self.tile_at(row, col) == wanted {
+                        row += 1;
+                    }
+                    runs.push((start, row, col));
+                } else {
+                    row += 1;
+                }
+            }
+        }
+
+        runs
+    }
+}
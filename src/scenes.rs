@@ -0,0 +1,365 @@
+//! Scene stack: decouples the gameplay loop from the resources and window
+//! that outlive it. `SharedGameState` holds what every scene might need
+//! (loaded images, live input, the active level); each `Scene` only holds
+//! what's specific to it and requests a transition by returning one from
+//! `tick`/`key_down`.
+
+use nalgebra as na;
+
+use ggez::{graphics, Context, GameResult, GameError};
+use ggez::event::{KeyCode, KeyMods};
+
+use gilrs::{Gilrs, Event as GilrsEvent, EventType as GilrsEventType, Axis, Button};
+
+use crate::camera::Frame;
+use crate::ecs::{
+    Bounds, EntityId, InputIntent, Manager, Patrol, PatrolAxis, PlayerState, Position, Renderable,
+    SlopeShape, Solid, TileImage, Velocity,
+};
+use crate::level::{Level, Tile};
+use crate::systems::{
+    ApplyVelocityXSystem, ApplyVelocityYSystem, BackgroundRenderSystem, CarryByPlatformSystem,
+    CollisionLeftRightSystem, CollisionSlopeSystem, CollisionUpDownSystem, PatrolSystem,
+    PlayerMovementSystem, PlayerPostCollisionSystem, PlayerRenderSystem, TileRenderSystem,
+};
+use crate::{
+    FALL_OFF_WORLD_TICKS, GAMEPAD_STICK_DEADZONE, MAX_FALL_SPEED, PATROL_SPEED, SCREEN_HEIGHT,
+    SCREEN_WIDTH, TILE_SIZE,
+};
+
+const DEFAULT_LEVEL: &str = "level1";
+
+/// State that survives scene transitions: the resources loaded once at
+/// startup, live input, and which level is active.
+pub struct SharedGameState {
+    pub player_image: graphics::Image,
+    pub platform_image: graphics::Image,
+    pub background_image: graphics::Image,
+
+    /// Held keyboard input, tracked separately from `gamepad_acceleration`
+    /// so that releasing a key doesn't stomp a simultaneously-held gamepad
+    /// axis (and vice versa); `input_acceleration` combines the two.
+    pub keyboard_acceleration: na::Vector2<f32>,
+    pub gamepad_acceleration: na::Vector2<f32>,
+    pub gilrs: Gilrs,
+
+    /// Name of the level `resources/levels/<level>.txt` to load for the next
+    /// `GameScene`.
+    pub level: String,
+}
+
+impl SharedGameState {
+    pub fn new(ctx: &mut Context) -> GameResult<SharedGameState> {
+        let player_image = graphics::Image::new(ctx, "/ball.png")?;
+        let platform_image = graphics::Image::new(ctx, "/platform.png")?;
+        let background_image = graphics::Image::new(ctx, "/background.png")?;
+
+        let gilrs = Gilrs::new().map_err(|e|
+            GameError::EventLoopError(format!("failed to initialize gilrs: {}", e)))?;
+
+        Ok(SharedGameState {
+            player_image,
+            platform_image,
+            background_image,
+            keyboard_acceleration: na::Vector2::new(0.0, 0.0),
+            gamepad_acceleration: na::Vector2::new(0.0, 0.0),
+            gilrs,
+            level: DEFAULT_LEVEL.to_string(),
+        })
+    }
+
+    pub fn poll_gamepad_input(&mut self) {
+        while let Some(GilrsEvent { event, .. }) = self.gilrs.next_event() {
+            match event {
+                GilrsEventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                    self.gamepad_acceleration.x = if f32::abs(value) < GAMEPAD_STICK_DEADZONE {
+                        0.0
+                    } else {
+                        value
+                    };
+                }
+                GilrsEventType::ButtonPressed(Button::South, _) => {
+                    self.gamepad_acceleration.y = 1.0;
+                }
+                GilrsEventType::ButtonReleased(Button::South, _) => {
+                    self.gamepad_acceleration.y = 0.0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Keyboard and gamepad input combined: whichever source is currently
+    /// non-zero wins, so a centered stick falls back to a held key and a
+    /// released key falls back to a tilted stick, instead of one silently
+    /// zeroing the other.
+    pub fn input_acceleration(&self) -> na::Vector2<f32> {
+        na::Vector2::new(
+            if self.gamepad_acceleration.x != 0.0 { self.gamepad_acceleration.x } else { self.keyboard_acceleration.x },
+            if self.gamepad_acceleration.y != 0.0 { self.gamepad_acceleration.y } else { self.keyboard_acceleration.y },
+        )
+    }
+}
+
+/// What a scene asks the `SceneStack` to do after a tick or input event.
+pub enum Transition {
+    None,
+    Push(Box<dyn Scene>),
+    Pop,
+    Replace(Box<dyn Scene>),
+}
+
+pub trait Scene {
+    fn tick(&mut self, shared: &mut SharedGameState, ctx: &mut Context) -> GameResult<Transition>;
+    fn draw(&self, shared: &SharedGameState, ctx: &mut Context) -> GameResult<()>;
+
+    fn key_down(&mut self, _shared: &mut SharedGameState, _ctx: &mut Context, _keycode: KeyCode, _keymods: KeyMods) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+}
+
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+}
+
+impl SceneStack {
+    pub fn new(initial: Box<dyn Scene>) -> SceneStack {
+        SceneStack { scenes: vec![initial] }
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop => { self.scenes.pop(); }
+            Transition::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            }
+        }
+    }
+
+    pub fn tick(&mut self, shared: &mut SharedGameState, ctx: &mut Context) -> GameResult<()> {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.tick(shared, ctx)?,
+            None => Transition::None,
+        };
+        self.apply(transition);
+        Ok(())
+    }
+
+    pub fn draw(&self, shared: &SharedGameState, ctx: &mut Context) -> GameResult<()> {
+        if let Some(scene) = self.scenes.last() {
+            scene.draw(shared, ctx)?;
+        }
+        Ok(())
+    }
+
+    pub fn key_down(&mut self, shared: &mut SharedGameState, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods) -> GameResult<()> {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.key_down(shared, ctx, keycode, keymods)?,
+            None => Transition::None,
+        };
+        self.apply(transition);
+        Ok(())
+    }
+}
+
+fn draw_centered_text(ctx: &mut Context, text: &str) -> GameResult<()> {
+    let fragment = graphics::Text::new(text);
+    let (width, height) = fragment.dimensions(ctx);
+    graphics::draw(ctx, &fragment,
+        (na::Point2::new(
+            SCREEN_WIDTH / 2.0 - width as f32 / 2.0,
+            SCREEN_HEIGHT / 2.0 - height as f32 / 2.0), ))
+}
+
+pub struct TitleScene;
+
+impl TitleScene {
+    pub fn new() -> TitleScene {
+        TitleScene
+    }
+}
+
+impl Scene for TitleScene {
+    fn tick(&mut self, _shared: &mut SharedGameState, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&self, _shared: &SharedGameState, ctx: &mut Context) -> GameResult<()> {
+        draw_centered_text(ctx, "iron-jump\npress any key to start")
+    }
+
+    fn key_down(&mut self, shared: &mut SharedGameState, ctx: &mut Context, _keycode: KeyCode, _keymods: KeyMods) -> GameResult<Transition> {
+        let level_name = shared.level.clone();
+        Ok(Transition::Push(Box::new(GameScene::new(ctx, &level_name)?)))
+    }
+}
+
+/// The gameplay scene: owns the ECS world for the current level and steps it
+/// once per tick. Entities live at their true world position; `camera`
+/// decides what part of that world is on screen.
+pub struct GameScene {
+    manager: Manager,
+    player_entity: EntityId,
+    camera: Frame,
+    fall_ticks: u32,
+    level_width: f32,
+    level_height: f32,
+}
+
+impl GameScene {
+    pub fn new(ctx: &mut Context, level_name: &str) -> GameResult<GameScene> {
+        let level = Level::load(ctx, level_name)?;
+
+        let mut manager = Manager::new();
+
+        for (start_col, end_col, row) in level.runs_of(Tile::Solid) {
+            let width_segments = (end_col - start_col) as i32;
+            let platform_entity = manager.spawn();
+            manager.positions.insert(platform_entity, Position { x: start_col as f32 * TILE_SIZE, y: row as f32 * TILE_SIZE });
+            manager.bounds.insert(platform_entity, Bounds { width: width_segments as f32 * TILE_SIZE, height: TILE_SIZE });
+            manager.solids.insert(platform_entity, Solid);
+            manager.renderables.insert(platform_entity, Renderable::Tiles { image: TileImage::Platform, width_segments, height_segments: 1 });
+        }
+
+        // A run of slope tiles is treated as one 45-degree ramp: it descends
+        // from its top-left corner to its bottom-right corner over its own
+        // width.
+        for (start_col, end_col, row) in level.runs_of(Tile::Slope) {
+            let width_segments = (end_col - start_col) as i32;
+            let slope_entity = manager.spawn();
+            manager.positions.insert(slope_entity, Position { x: start_col as f32 * TILE_SIZE, y: row as f32 * TILE_SIZE });
+            manager.slopes.insert(slope_entity, SlopeShape { width_segments, height_segments: width_segments, rising: false });
+            manager.renderables.insert(slope_entity, Renderable::Tiles { image: TileImage::Platform, width_segments, height_segments: width_segments });
+        }
+
+        // A run of horizontal-patrol tiles becomes one tile-sized platform
+        // that paces between the run's ends; a vertical run becomes an
+        // elevator pacing up and down instead.
+        for (start_col, end_col, row) in level.runs_of(Tile::PatrolHorizontal) {
+            let min_x = start_col as f32 * TILE_SIZE;
+            let max_x = (end_col - 1) as f32 * TILE_SIZE;
+            let platform_entity = manager.spawn();
+            manager.positions.insert(platform_entity, Position { x: min_x, y: row as f32 * TILE_SIZE });
+            manager.velocities.insert(platform_entity, Velocity { x: PATROL_SPEED, y: 0.0 });
+            manager.bounds.insert(platform_entity, Bounds { width: TILE_SIZE, height: TILE_SIZE });
+            manager.solids.insert(platform_entity, Solid);
+            manager.patrols.insert(platform_entity, Patrol { axis: PatrolAxis::Horizontal, min: min_x, max: max_x });
+            manager.renderables.insert(platform_entity, Renderable::Tiles { image: TileImage::Platform, width_segments: 1, height_segments: 1 });
+        }
+
+        for (start_row, end_row, col) in level.vertical_runs_of(Tile::PatrolVertical) {
+            let min_y = start_row as f32 * TILE_SIZE;
+            let max_y = (end_row - 1) as f32 * TILE_SIZE;
+            let platform_entity = manager.spawn();
+            manager.positions.insert(platform_entity, Position { x: col as f32 * TILE_SIZE, y: min_y });
+            manager.velocities.insert(platform_entity, Velocity { x: 0.0, y: PATROL_SPEED });
+            manager.bounds.insert(platform_entity, Bounds { width: TILE_SIZE, height: TILE_SIZE });
+            manager.solids.insert(platform_entity, Solid);
+            manager.patrols.insert(platform_entity, Patrol { axis: PatrolAxis::Vertical, min: min_y, max: max_y });
+            manager.renderables.insert(platform_entity, Renderable::Tiles { image: TileImage::Platform, width_segments: 1, height_segments: 1 });
+        }
+
+        let (player_x, player_y) = level.player_spawn;
+
+        let player_entity = manager.spawn();
+        manager.positions.insert(player_entity, Position { x: player_x, y: player_y });
+        manager.velocities.insert(player_entity, Velocity { x: 0.0, y: 0.0 });
+        manager.bounds.insert(player_entity, Bounds { width: TILE_SIZE, height: TILE_SIZE });
+        manager.input_intents.insert(player_entity, InputIntent { acceleration: na::Vector2::new(0.0, 0.0) });
+        manager.player_states.insert(player_entity, PlayerState {
+            jumping: false, alpha: 1.0, rotation: 0.0, speed_up_counter: 0, supporting_platform: None,
+        });
+        manager.renderables.insert(player_entity, Renderable::Player);
+
+        manager.add_system(PlayerMovementSystem);
+        manager.add_system(PatrolSystem);
+        manager.add_system(CarryByPlatformSystem { player: player_entity });
+        manager.add_system(ApplyVelocityXSystem);
+        manager.add_system(CollisionLeftRightSystem { player: player_entity });
+        manager.add_system(ApplyVelocityYSystem);
+        manager.add_system(CollisionUpDownSystem { player: player_entity });
+        manager.add_system(CollisionSlopeSystem { player: player_entity });
+        manager.add_system(PlayerPostCollisionSystem { player: player_entity });
+
+        manager.add_render_system(BackgroundRenderSystem);
+        manager.add_render_system(TileRenderSystem);
+        manager.add_render_system(PlayerRenderSystem { player: player_entity });
+
+        let level_width = level.width();
+        let level_height = level.height();
+
+        let mut camera = Frame::new();
+        let target = na::Vector2::new(
+            player_x + TILE_SIZE / 2.0 - SCREEN_WIDTH / 2.0,
+            player_y + TILE_SIZE / 2.0 - SCREEN_HEIGHT / 2.0);
+        camera.position = target;
+        camera.update(target, level_width, level_height);
+
+        Ok(GameScene {
+            manager,
+            player_entity,
+            camera,
+            fall_ticks: 0,
+            level_width,
+            level_height,
+        })
+    }
+}
+
+impl Scene for GameScene {
+    fn tick(&mut self, shared: &mut SharedGameState, _ctx: &mut Context) -> GameResult<Transition> {
+        if let Some(intent) = self.manager.input_intents.get_mut(&self.player_entity) {
+            intent.acceleration = shared.input_acceleration();
+        }
+
+        self.manager.run_systems();
+
+        if let Some(position) = self.manager.positions.get(&self.player_entity) {
+            let target = na::Vector2::new(
+                position.x + TILE_SIZE / 2.0 - SCREEN_WIDTH / 2.0,
+                position.y + TILE_SIZE / 2.0 - SCREEN_HEIGHT / 2.0);
+            self.camera.update(target, self.level_width, self.level_height);
+        }
+
+        let still_falling = self.manager.velocities.get(&self.player_entity)
+            .map_or(false, |velocity| velocity.y <= MAX_FALL_SPEED);
+
+        self.fall_ticks = if still_falling { self.fall_ticks + 1 } else { 0 };
+
+        if self.fall_ticks > FALL_OFF_WORLD_TICKS {
+            return Ok(Transition::Replace(Box::new(GameOverScene::new())));
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&self, shared: &SharedGameState, ctx: &mut Context) -> GameResult<()> {
+        self.manager.draw(shared, self.camera.position, ctx)
+    }
+}
+
+pub struct GameOverScene;
+
+impl GameOverScene {
+    pub fn new() -> GameOverScene {
+        GameOverScene
+    }
+}
+
+impl Scene for GameOverScene {
+    fn tick(&mut self, _shared: &mut SharedGameState, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&self, _shared: &SharedGameState, ctx: &mut Context) -> GameResult<()> {
+        draw_centered_text(ctx, "game over\npress any key to restart")
+    }
+
+    fn key_down(&mut self, shared: &mut SharedGameState, ctx: &mut Context, _keycode: KeyCode, _keymods: KeyMods) -> GameResult<Transition> {
+        let level_name = shared.level.clone();
+        Ok(Transition::Push(Box::new(GameScene::new(ctx, &level_name)?)))
+    }
+}
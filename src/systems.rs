@@ -0,0 +1,425 @@
+//! Concrete gameplay systems built on top of the generic `ecs` core: player
+//! movement, the velocity/collision pipeline, and rendering.
+
+use std::f32;
+
+use nalgebra as na;
+
+use ggez::{graphics, Context, GameResult};
+
+use crate::ecs::{EntityId, Manager, PatrolAxis, Renderable, System, RenderSystem, TileImage};
+use crate::scenes::SharedGameState;
+use crate::{
+    draw_tiles, rect_intersection, rect_is_empty_with_tolerance, slope_floor_y,
+    ACCELERATION, BACKGROUND_PARALLAX_FACTOR, CHANGE_DIRECTION_SPEED, COLLISION_TOLERANCE,
+    DECELERATION, MAX_FALL_SPEED, MAX_SPEED, MAX_SPEEDUP_COUNT, SCREEN_HEIGHT, SCREEN_WIDTH,
+    SPEED_POWER_UP, TILE_SIZE, UP_SPEED,
+};
+
+/// Applies the player's per-tick movement rules (acceleration, deceleration,
+/// jump and gravity, the idle pulse animation) to every entity carrying
+/// `InputIntent`.
+pub struct PlayerMovementSystem;
+
+impl System for PlayerMovementSystem {
+    fn update(&self, manager: &mut Manager) {
+        let ids: Vec<EntityId> = manager.input_intents.keys().cloned().collect();
+
+        for id in ids {
+            let acceleration = manager.input_intents[&id].acceleration;
+            let mut move_left_or_right = false;
+
+            {
+                let player_state = manager.player_states.get_mut(&id).expect("input entity must carry PlayerState");
+                if player_state.speed_up_counter > 0 {
+                    player_state.speed_up_counter += 1;
+                    if player_state.speed_up_counter > MAX_SPEEDUP_COUNT {
+                        player_state.speed_up_counter = 0;
+                    }
+                }
+            }
+
+            let current_max_speed = {
+                let player_state = &manager.player_states[&id];
+                if player_state.speed_up_counter > 0 { MAX_SPEED * SPEED_POWER_UP } else { MAX_SPEED }
+            };
+
+            let velocity = manager.velocities.get_mut(&id).expect("input entity must carry Velocity");
+
+            if acceleration.x < 0.0 {
+                if velocity.x < 0.0 {
+                    velocity.x += f32::abs(acceleration.x) * ACCELERATION * CHANGE_DIRECTION_SPEED;
+                }
+                velocity.x += f32::abs(acceleration.x) * ACCELERATION;
+                if velocity.x > current_max_speed {
+                    velocity.x = current_max_speed;
+                }
+                move_left_or_right = true;
+            }
+            else if acceleration.x > 0.0 {
+                if velocity.x > 0.0 {
+                    velocity.x -= f32::abs(acceleration.x) * ACCELERATION * CHANGE_DIRECTION_SPEED;
+                }
+                velocity.x -= f32::abs(acceleration.x) * ACCELERATION;
+                if velocity.x < -current_max_speed {
+                    velocity.x = -current_max_speed;
+                }
+                move_left_or_right = true;
+            }
+
+            let player_state = manager.player_states.get_mut(&id).unwrap();
+
+            if !player_state.jumping && acceleration.y > 0.0 {
+                if velocity.y < UP_SPEED {
+                    velocity.y = UP_SPEED;
+                }
+                player_state.jumping = true;
+            }
+
+            if !move_left_or_right {
+                if f32::abs(velocity.x) < DECELERATION {
+                    velocity.x = 0.0;
+                }
+                else if velocity.x > 0.0 {
+                    velocity.x -= DECELERATION;
+                }
+                else if velocity.x < 0.0 {
+                    velocity.x += DECELERATION;
+                }
+            }
+
+            velocity.y -= DECELERATION;
+            if velocity.y < MAX_FALL_SPEED {
+                velocity.y = MAX_FALL_SPEED;
+            }
+            player_state.jumping = true;
+
+            player_state.alpha += 0.07;
+            if player_state.alpha > f32::consts::PI {
+                player_state.alpha -= f32::consts::PI;
+            }
+        }
+    }
+}
+
+/// Paces every `Patrol` entity between its `min`/`max` bound, reversing its
+/// own `Velocity` at each end. Runs before `ApplyVelocityXSystem`/
+/// `ApplyVelocityYSystem` so a direction reversal takes effect the same tick
+/// it happens.
+pub struct PatrolSystem;
+
+impl System for PatrolSystem {
+    fn update(&self, manager: &mut Manager) {
+        let ids: Vec<EntityId> = manager.patrols.keys().cloned().collect();
+
+        for id in ids {
+            let (axis, min, max) = match manager.patrols.get(&id) {
+                Some(patrol) => (patrol.axis, patrol.min, patrol.max),
+                None => continue,
+            };
+
+            let position = match manager.positions.get_mut(&id) { Some(p) => p, None => continue };
+            let velocity = match manager.velocities.get_mut(&id) { Some(v) => v, None => continue };
+
+            // `ApplyVelocityXSystem`/`ApplyVelocityYSystem` integrate as
+            // `position -= velocity`, so a positive velocity drives the
+            // position DOWN towards `min`; the bound checks below are
+            // mirrored from the naive `position += velocity` reading to
+            // match.
+            match axis {
+                PatrolAxis::Horizontal => {
+                    if position.x <= min && velocity.x > 0.0 {
+                        position.x = min;
+                        velocity.x = -velocity.x;
+                    } else if position.x >= max && velocity.x < 0.0 {
+                        position.x = max;
+                        velocity.x = -velocity.x;
+                    }
+                }
+                PatrolAxis::Vertical => {
+                    if position.y <= min && velocity.y > 0.0 {
+                        position.y = min;
+                        velocity.y = -velocity.y;
+                    } else if position.y >= max && velocity.y < 0.0 {
+                        position.y = max;
+                        velocity.y = -velocity.y;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// If the player ended the previous tick resting on a moving platform,
+/// carries it along by that platform's displacement this tick, before
+/// collision resolves — otherwise normal collision only stops the player
+/// sinking into the platform, it doesn't stop the platform sliding out from
+/// under a player that isn't itself moving.
+pub struct CarryByPlatformSystem { pub player: EntityId }
+
+impl System for CarryByPlatformSystem {
+    fn update(&self, manager: &mut Manager) {
+        let supporting_platform = match manager.player_states.get(&self.player) {
+            Some(state) => state.supporting_platform,
+            None => return,
+        };
+
+        let platform = match supporting_platform { Some(id) => id, None => return };
+        let delta = match manager.velocities.get(&platform) { Some(v) => *v, None => return };
+
+        // Match `ApplyVelocityXSystem`/`ApplyVelocityYSystem`'s integration
+        // sign so the player inherits the same displacement the platform
+        // itself is moved by later this tick.
+        if let Some(position) = manager.positions.get_mut(&self.player) {
+            position.x -= delta.x;
+            position.y -= delta.y;
+        }
+    }
+}
+
+/// Applies every entity's horizontal velocity to its own world position:
+/// the player and any moving platform alike. Integrates as
+/// `position -= velocity` to match the sign `CollisionLeftRightSystem`/
+/// `CollisionUpDownSystem` already resolve offsets with in this y-down world.
+pub struct ApplyVelocityXSystem;
+
+impl System for ApplyVelocityXSystem {
+    fn update(&self, manager: &mut Manager) {
+        let ids: Vec<EntityId> = manager.velocities.keys().cloned().collect();
+        for id in ids {
+            let dx = manager.velocities[&id].x;
+            if let Some(position) = manager.positions.get_mut(&id) {
+                position.x -= dx;
+            }
+        }
+    }
+}
+
+/// Applies every entity's vertical velocity to its own world position. See
+/// `ApplyVelocityXSystem` for the integration sign.
+pub struct ApplyVelocityYSystem;
+
+impl System for ApplyVelocityYSystem {
+    fn update(&self, manager: &mut Manager) {
+        let ids: Vec<EntityId> = manager.velocities.keys().cloned().collect();
+        for id in ids {
+            let dy = manager.velocities[&id].y;
+            if let Some(position) = manager.positions.get_mut(&id) {
+                position.y -= dy;
+            }
+        }
+    }
+}
+
+/// Resolves `player` against every `Solid` entity's left/right edges.
+pub struct CollisionLeftRightSystem { pub player: EntityId }
+
+impl System for CollisionLeftRightSystem {
+    fn update(&self, manager: &mut Manager) {
+        let player_rect = match manager.rect_of(self.player) { Some(r) => r, None => return };
+
+        let mut is_colliding = false;
+        let mut offset_x = 0.0;
+
+        let solid_ids: Vec<EntityId> = manager.solids.keys().cloned().collect();
+        for id in solid_ids {
+            let rect = match manager.rect_of(id) { Some(r) => r, None => continue };
+            let intersection = rect_intersection(rect, player_rect);
+            if rect_is_empty_with_tolerance(intersection) {
+                continue;
+            }
+
+            if rect.left() > player_rect.left() {
+                offset_x = intersection.w;
+                is_colliding = true;
+            }
+            else if rect.right() < player_rect.right() {
+                offset_x = -intersection.w;
+                is_colliding = true;
+            }
+        }
+
+        if is_colliding {
+            if let Some(position) = manager.positions.get_mut(&self.player) {
+                position.x -= offset_x;
+            }
+            if let Some(velocity) = manager.velocities.get_mut(&self.player) {
+                velocity.x = 0.0;
+            }
+        }
+    }
+}
+
+/// Resolves `player` against every `Solid` entity's floor/ceiling.
+pub struct CollisionUpDownSystem { pub player: EntityId }
+
+impl System for CollisionUpDownSystem {
+    fn update(&self, manager: &mut Manager) {
+        let player_rect = match manager.rect_of(self.player) { Some(r) => r, None => return };
+
+        let mut is_colliding = false;
+        let mut offset_y = 0.0;
+        let mut landed_on: Option<EntityId> = None;
+
+        let solid_ids: Vec<EntityId> = manager.solids.keys().cloned().collect();
+        for id in solid_ids {
+            let rect = match manager.rect_of(id) { Some(r) => r, None => continue };
+            let intersection = rect_intersection(rect, player_rect);
+            if rect_is_empty_with_tolerance(intersection) {
+                continue;
+            }
+
+            let player_velocity_y = manager.velocities.get(&self.player).map_or(0.0, |v| v.y);
+
+            if rect.bottom() < player_rect.bottom() {
+                if player_velocity_y > 0.0 {
+                    if let Some(velocity) = manager.velocities.get_mut(&self.player) {
+                        velocity.y = 0.0;
+                    }
+                }
+
+                offset_y = -intersection.h;
+                is_colliding = true;
+            }
+            else if player_velocity_y < 0.0 {
+                if rect.top() > player_rect.bottom() - COLLISION_TOLERANCE + player_velocity_y {
+                    if let Some(velocity) = manager.velocities.get_mut(&self.player) {
+                        velocity.y = 0.0;
+                    }
+                    if let Some(player_state) = manager.player_states.get_mut(&self.player) {
+                        player_state.jumping = false;
+                    }
+                    offset_y = intersection.h;
+                    is_colliding = true;
+                    landed_on = Some(id);
+                }
+            }
+            else if rect.top() > player_rect.bottom() - COLLISION_TOLERANCE + player_velocity_y {
+                if let Some(player_state) = manager.player_states.get_mut(&self.player) {
+                    player_state.jumping = false;
+                }
+                offset_y = intersection.h;
+                is_colliding = true;
+                landed_on = Some(id);
+            }
+        }
+
+        if is_colliding {
+            if let Some(position) = manager.positions.get_mut(&self.player) {
+                position.y -= offset_y;
+            }
+        }
+
+        if let Some(player_state) = manager.player_states.get_mut(&self.player) {
+            player_state.supporting_platform = landed_on;
+        }
+    }
+}
+
+/// Rolls `player` up and down any `SlopeShape` entity it overlaps. Runs after
+/// `CollisionLeftRightSystem`/`CollisionUpDownSystem` so flat collision is
+/// already resolved; slopes only ever act as a floor, never a wall.
+pub struct CollisionSlopeSystem { pub player: EntityId }
+
+impl System for CollisionSlopeSystem {
+    fn update(&self, manager: &mut Manager) {
+        let player_rect = match manager.rect_of(self.player) { Some(r) => r, None => return };
+        let player_bounds = match manager.bounds.get(&self.player) { Some(b) => *b, None => return };
+        let player_center_x = player_rect.left() + player_rect.w / 2.0;
+        let player_velocity_y = manager.velocities.get(&self.player).map_or(0.0, |v| v.y);
+
+        let mut snap_floor_y: Option<f32> = None;
+
+        for (&id, slope) in manager.slopes.iter() {
+            let position = match manager.positions.get(&id) { Some(p) => p, None => continue };
+
+            let x0 = position.x;
+            let x1 = position.x + slope.width_segments as f32 * TILE_SIZE;
+            if player_center_x < x0 || player_center_x > x1 {
+                continue;
+            }
+
+            let floor_y = slope_floor_y(slope, position, player_center_x);
+
+            if player_velocity_y <= 0.0 && player_rect.bottom() >= floor_y - COLLISION_TOLERANCE {
+                snap_floor_y = Some(floor_y);
+            }
+        }
+
+        if let Some(floor_y) = snap_floor_y {
+            if let Some(position) = manager.positions.get_mut(&self.player) {
+                position.y = floor_y - player_bounds.height;
+            }
+            if let Some(velocity) = manager.velocities.get_mut(&self.player) {
+                velocity.y = 0.0;
+            }
+            if let Some(player_state) = manager.player_states.get_mut(&self.player) {
+                player_state.jumping = false;
+            }
+        }
+    }
+}
+
+/// Leans `player`'s sprite into its horizontal velocity, once collision has
+/// settled for the tick.
+pub struct PlayerPostCollisionSystem { pub player: EntityId }
+
+impl System for PlayerPostCollisionSystem {
+    fn update(&self, manager: &mut Manager) {
+        let velocity_x = manager.velocities.get(&self.player).map_or(0.0, |v| v.x);
+        if let Some(player_state) = manager.player_states.get_mut(&self.player) {
+            let unit_velocity = velocity_x / (TILE_SIZE / 2.0);
+            player_state.rotation -= unit_velocity * 0.55;
+        }
+    }
+}
+
+/// Draws the tiled background, parallax-scrolled at `BACKGROUND_PARALLAX_FACTOR`
+/// of the camera's own motion rather than living at a world position of its
+/// own.
+pub struct BackgroundRenderSystem;
+
+impl RenderSystem for BackgroundRenderSystem {
+    fn draw(&self, _manager: &Manager, shared: &SharedGameState, camera: na::Vector2<f32>, ctx: &mut Context) -> GameResult<()> {
+        let parallax = camera * BACKGROUND_PARALLAX_FACTOR;
+
+        let offset_x = parallax.x % TILE_SIZE - TILE_SIZE;
+        let offset_y = parallax.y % TILE_SIZE - TILE_SIZE;
+
+        draw_tiles(ctx, &shared.background_image,
+            offset_x, offset_y,
+            SCREEN_WIDTH as i32 / TILE_SIZE as i32 + 3,
+            SCREEN_HEIGHT as i32 / TILE_SIZE as i32 + 2)
+    }
+}
+
+pub struct TileRenderSystem;
+
+impl RenderSystem for TileRenderSystem {
+    fn draw(&self, manager: &Manager, shared: &SharedGameState, camera: na::Vector2<f32>, ctx: &mut Context) -> GameResult<()> {
+        for (&id, renderable) in manager.renderables.iter() {
+            if let Renderable::Tiles { image, width_segments, height_segments } = renderable {
+                let position = match manager.positions.get(&id) { Some(p) => p, None => continue };
+                let tile_image = match image { TileImage::Platform => &shared.platform_image };
+                draw_tiles(ctx, tile_image, position.x - camera.x, position.y - camera.y, *width_segments, *height_segments)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct PlayerRenderSystem { pub player: EntityId }
+
+impl RenderSystem for PlayerRenderSystem {
+    fn draw(&self, manager: &Manager, shared: &SharedGameState, camera: na::Vector2<f32>, ctx: &mut Context) -> GameResult<()> {
+        let position = match manager.positions.get(&self.player) { Some(p) => p, None => return Ok(()) };
+        let player_state = match manager.player_states.get(&self.player) { Some(s) => s, None => return Ok(()) };
+
+        graphics::draw(ctx, &shared.player_image,
+            graphics::DrawParam::new()
+                .dest(na::Point2::new(position.x - camera.x + TILE_SIZE / 2.0, position.y - camera.y + TILE_SIZE / 2.0))
+                .rotation(player_state.rotation)
+                .offset(na::Point2::new(0.5, 0.5))
+            )
+    }
+}